@@ -1,22 +1,44 @@
-use std::os::unix::io::RawFd;
+use std::cell::Cell;
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use errno;
+#[cfg(unix)]
+use std::os::unix::io::RawFd as NativeHandle;
+#[cfg(windows)]
+use std::os::windows::io::RawHandle as NativeHandle;
 
 pub use util::{Kind, Mode, ParseError};
 pub use functions::Error;
 use functions;
 
 
-/// Represents a write lock on a file.
+/// Represents a lock on a file.
 ///
-/// The `lock(Kind)` method tries to obtain a write-lock on the
-/// file identified by a file-descriptor. 
-/// One can obtain different kinds of write-locks.
+/// The `lock(Kind, Mode)` method tries to obtain a lock on the file
+/// identified by a native file handle (a `RawFd` on Unix, a `RawHandle` on
+/// Windows). `Mode` selects whether the lock is shared or exclusive, and
+/// `Kind` selects whether obtaining it blocks.
+///
+/// * Mode::Read - a shared lock. Any number of processes may hold a `Read`
+/// lock on the same file at once.
+/// * Mode::Write - an exclusive lock. Only one process may hold it, and only
+/// if no other process holds a `Read` or `Write` lock.
 ///
 /// * Kind::NonBlocking - immediately return with an `Errno` error.
 /// * Kind::Blocking - waits (i.e. blocks the running thread) for the current
 /// owner of the lock to relinquish the lock.
 ///
+/// Calling `lock` again on a `Lock` that already holds a lock re-locks the
+/// file descriptor with the new `Mode`. On Unix this is atomic: a `Write`
+/// lock can be downgraded to `Read`, or a `Read` lock upgraded to `Write`,
+/// without ever leaving the file briefly unlocked.
+///
+/// `Lock` remembers the range it last locked (the whole file, by default)
+/// and `unlock`/`Drop` release exactly that range, so a `Lock` that is
+/// re-used for several `lock_range` calls never unlocks a range it didn't
+/// itself acquire.
+///
 /// # Example
 ///
 /// Please note that the examples use `tempfile` merely to quickly create a file
@@ -49,35 +71,98 @@ use functions;
 /// ```
 #[derive(Debug, Eq, PartialEq)]
 pub struct Lock {
-    fd: RawFd,
+    fd:    NativeHandle,
+    range: Cell<(u64, u64)>,
 }
 
 impl Lock {
-    /// Create a new lock instance from the given file descriptor `fd`.
-    /// 
+    /// Create a new lock instance from the given native file handle `fd`
+    /// (a `RawFd` on Unix, a `RawHandle` on Windows).
+    ///
     /// You will have to call `lock(...)` on it to acquire any lock.
-    pub fn new(fd: RawFd) -> Lock {
+    pub fn new(fd: NativeHandle) -> Lock {
         Lock {
-            fd:   fd,
+            fd:    fd,
+            range: Cell::new((0, 0)),
         }
     }
 
     /// Obtain a write-lock the file-descriptor
-    /// 
+    /// (with `Mode::Read`, a shared read-lock instead) over the whole file.
+    ///
     /// For an example, please see the documentation of the [`Lock`](struct.Lock.html) structure.
     pub fn lock(&self, kind: Kind, mode: Mode) -> Result<(), Error> {
-        functions::lock(self.fd, kind.clone(), mode.clone())
+        self.lock_range(kind, mode, 0, 0)
     }
 
-    /// Unlocks the file held by `Lock`.
+    /// Locks the byte range `[offset, offset + len)` of the file instead of
+    /// the whole file, or the range from `offset` to the end of the file
+    /// when `len == 0`. `lock(kind, mode)` is equivalent to
+    /// `lock_range(kind, mode, 0, 0)`.
+    ///
+    /// This lets independent regions of one file be locked by different
+    /// processes concurrently, for record-level (database-style) locking.
+    pub fn lock_range(&self, kind: Kind, mode: Mode, offset: u64, len: u64) -> Result<(), Error> {
+        match functions::lock_range(self.fd, kind.clone(), mode.clone(), offset, len) {
+            Ok(())  => { self.range.set((offset, len)); Ok(()) },
+            Err(e)  => Err(e),
+        }
+    }
+
+    /// Attempts to obtain the lock in `mode`, retrying until either it
+    /// succeeds or `timeout` elapses, in which case `Error::WouldBlock` is
+    /// returned.
+    ///
+    /// This is implemented as repeated non-blocking `lock` attempts with
+    /// exponential backoff (starting at ~1ms, capped at ~50ms) rather than
+    /// a single `Kind::Blocking` call, since the underlying OS primitive
+    /// blocks uninterruptibly and cannot be made to respect a timer.
+    ///
+    /// A `timeout` too large for `Instant` to represent (e.g. close to
+    /// `Duration::from_secs(u64::MAX)`) is treated as "retry indefinitely"
+    /// rather than panicking.
+    pub fn lock_timeout(&self, mode: Mode, timeout: Duration) -> Result<(), Error> {
+        let deadline    = Instant::now().checked_add(timeout);
+        let max_backoff = Duration::from_millis(50);
+        let mut backoff = Duration::from_millis(1);
+
+        loop {
+            match self.lock(Kind::NonBlocking, mode.clone()) {
+                Ok(())                 => return Ok(()),
+                Err(Error::WouldBlock) => {
+                    if let Some(deadline) = deadline {
+                        if Instant::now() >= deadline {
+                            return Err(Error::WouldBlock);
+                        }
+                    }
+
+                    thread::sleep(backoff);
+                    backoff = cmp::min(backoff * 2, max_backoff);
+                },
+                Err(e)                 => return Err(e),
+            }
+        }
+    }
+
+    /// Unlocks exactly the range this `Lock` last locked (the whole file,
+    /// by default, or whatever range the most recent `lock`/`lock_range`
+    /// call used).
     ///
     /// In reality, you shouldn't need to call `unlock()`. As `Lock` implements
     /// the `Drop` trait, once the `Lock` reference goes out of scope, `unlock()`
     /// will be called automatically.
     ///
     /// For an example, please see the documentation of the [`Lock`](struct.Lock.html) structure.
-    pub fn unlock(&self) -> Result<(), errno::Errno> {
-        functions::unlock(self.fd)
+    pub fn unlock(&self) -> Result<(), Error> {
+        let (offset, len) = self.range.get();
+        self.unlock_range(offset, len)
+    }
+
+    /// Unlocks the byte range `[offset, offset + len)` of the file, or the
+    /// range from `offset` to the end of the file when `len == 0`,
+    /// regardless of which range this `Lock` last locked.
+    pub fn unlock_range(&self, offset: u64, len: u64) -> Result<(), Error> {
+        functions::unlock_range(self.fd, offset, len)
     }
 }
 
@@ -87,3 +172,165 @@ impl Drop for Lock {
         self.unlock().ok();
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::ptr;
+    use libc;
+
+    /// Runs `child` in a forked child process and reports what it returned
+    /// back to the caller: `0` for `Ok(())`, `1` for `Err(Error::WouldBlock)`,
+    /// `2` for any other error. `fcntl` locks are scoped to a process, so
+    /// exercising contention between two `Lock`s genuinely requires two
+    /// processes rather than two threads or two descriptors in one process.
+    fn run_in_child<F: FnOnce() -> Result<(), Error>>(child: F) -> u8 {
+        let (mut parent_sock, mut child_sock) = UnixStream::pair().unwrap();
+
+        match unsafe { libc::fork() } {
+            0 => {
+                let code = match child() {
+                    Ok(())                 => 0u8,
+                    Err(Error::WouldBlock) => 1u8,
+                    Err(_)                 => 2u8,
+                };
+                child_sock.write_all(&[code]).unwrap();
+                unsafe { libc::_exit(0) };
+            },
+            pid if pid > 0 => {
+                let mut code = [0u8; 1];
+                parent_sock.read_exact(&mut code).unwrap();
+                unsafe { libc::waitpid(pid, ptr::null_mut(), 0) };
+                code[0]
+            },
+            _ => panic!("fork() failed"),
+        }
+    }
+
+    #[test]
+    fn shared_read_locks_coexist() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock(Kind::Blocking, Mode::Read).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock(Kind::NonBlocking, Mode::Read)
+        });
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn exclusive_write_lock_excludes_others() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock(Kind::Blocking, Mode::Write).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock(Kind::NonBlocking, Mode::Write)
+        });
+
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    fn independent_ranges_do_not_contend() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock_range(Kind::Blocking, Mode::Write, 0, 10).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock_range(Kind::NonBlocking, Mode::Write, 10, 10)
+        });
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn overlapping_range_is_excluded() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock_range(Kind::Blocking, Mode::Write, 0, 10).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock_range(Kind::NonBlocking, Mode::Write, 5, 10)
+        });
+
+        assert_eq!(result, 1);
+    }
+
+    /// Regression test: a `Lock` must only release the range it actually
+    /// locked. Before `Lock` tracked its range, `unlock`/`Drop` always
+    /// released the whole file, so locking one range and dropping a
+    /// *different* `Lock` on the same fd (or process, on Unix) would release
+    /// a range it never held.
+    #[test]
+    fn dropping_one_lock_does_not_release_a_different_range() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock_range(Kind::Blocking, Mode::Write, 0, 10).unwrap();
+
+        {
+            let scoped = Lock::new(f.as_raw_fd());
+            scoped.lock_range(Kind::Blocking, Mode::Write, 10, 10).unwrap();
+        }
+        // `scoped` just dropped and unlocked `[10, 20)`. `held`'s lock on
+        // `[0, 10)` must still be in effect.
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock_range(Kind::NonBlocking, Mode::Write, 0, 10)
+        });
+
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    fn lock_timeout_expires_while_held() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock(Kind::Blocking, Mode::Write).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock_timeout(Mode::Write, Duration::from_millis(50))
+        });
+
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    fn lock_timeout_succeeds_once_released() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock(Kind::Blocking, Mode::Write).unwrap();
+        held.unlock().unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            Lock::new(other.as_raw_fd()).lock_timeout(Mode::Write, Duration::from_millis(50))
+        });
+
+        assert_eq!(result, 0);
+    }
+
+    /// Regression test: an unrepresentable deadline (`Instant::now() +
+    /// timeout` overflowing) must not panic; it should be treated as
+    /// "retry indefinitely" instead.
+    #[test]
+    fn lock_timeout_does_not_panic_on_unrepresentable_deadline() {
+        let f = ::tempfile::tempfile().unwrap();
+        let lock = Lock::new(f.as_raw_fd());
+
+        let result = lock.lock_timeout(Mode::Write, Duration::from_secs(u64::max_value()));
+
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -1,18 +1,17 @@
 //! Contains the actual functional lock implementation
 use std::error::Error as ErrorTrait;
-use std::os::unix::io::RawFd;
 use std::fmt;
 use errno;
-use libc::{self, c_int};
 
 pub use util::{Kind, Mode, ParseError};
+use sys;
 
-const WOULD_BLOCK_MSG: &'static str = "Lock is already taken by another process";
+#[cfg(unix)]
+use std::os::unix::io::RawFd as NativeHandle;
+#[cfg(windows)]
+use std::os::windows::io::RawHandle as NativeHandle;
 
-extern {
-    fn c_lock(fd: i32, should_block: i32, is_write_lock: i32) -> c_int;
-    fn c_unlock(fd: i32) -> c_int;
-}
+const WOULD_BLOCK_MSG: &'static str = "Lock is already taken by another process";
 
 /// Represents the error that occurred while trying to lock or unlock a file.
 #[derive(Debug, Eq, PartialEq)]
@@ -20,9 +19,13 @@ pub enum Error {
     /// Indicates that attempting to acquire the lock in Blocking mode would block.
     /// This variant is used only if non-blocking lock acquisition failed.
     WouldBlock,
-    /// caused when the error occurred at the filesystem layer (see
+    /// caused when the error occurred at the filesystem layer on Unix (see
     /// [errno](https://crates.io/crates/errno)).
     Errno(errno::Errno),
+    /// caused when the error occurred at the OS layer on a platform that has
+    /// no `errno`, carrying the raw OS error code (e.g. `GetLastError()` on
+    /// Windows).
+    Os(i32),
 }
 
 impl fmt::Display for Error {
@@ -31,7 +34,9 @@ impl fmt::Display for Error {
             Error::WouldBlock
                 => WOULD_BLOCK_MSG.fmt(f),
             Error::Errno(ref errno)
-                => write!(f, "Lock operation failed: {}", errno)
+                => write!(f, "Lock operation failed: {}", errno),
+            Error::Os(code)
+                => write!(f, "Lock operation failed with OS error {}", code),
         }
     }
 }
@@ -41,41 +46,32 @@ impl ErrorTrait for Error {
         match *self {
             Error::WouldBlock
                 => WOULD_BLOCK_MSG,
-            Error::Errno(_) 
+            Error::Errno(_)
+                => "Failed to acuire file lock",
+            Error::Os(_)
                 => "Failed to acuire file lock",
         }
     }
 }
 
-/// Obtain a write-lock the file-descriptor
-/// 
+/// Locks the byte range `[offset, offset + len)` of the file-descriptor, or
+/// from `offset` to the end of the file when `len == 0`. A whole-file lock
+/// is `lock_range(fd, kind, mode, 0, 0)`.
+///
 /// For an example, please see the documentation of the [`Lock`](struct.Lock.html) structure.
-pub fn lock(fd: RawFd, kind: Kind, mode: Mode) -> Result<(), Error> {
-    let errno = unsafe { c_lock(fd, kind.into(), mode.into()) };
-
-    return match errno {
-       0 => Ok(()),
-       libc::consts::os::posix88::EAGAIN => Err(Error::WouldBlock),
-       _ => Err(Error::Errno(errno::Errno(errno))),
-    }
+pub fn lock_range(fd: NativeHandle, kind: Kind, mode: Mode, offset: u64, len: u64) -> Result<(), Error> {
+    sys::lock_range(fd, kind, mode, offset, len)
 }
 
-/// Unlocks the file held by `Lock`.
+/// Unlocks exactly the byte range `[offset, offset + len)` of the
+/// file-descriptor (the range a prior `lock_range` call locked), or the
+/// range from `offset` to the end of the file when `len == 0`.
 ///
-/// In reality, you shouldn't need to call `unlock()`. As `Lock` implements
-/// the `Drop` trait, once the `Lock` reference goes out of scope, `unlock()`
-/// will be called automatically.
+/// In reality, you shouldn't need to call this directly. As `Lock`
+/// implements the `Drop` trait, once the `Lock` reference goes out of
+/// scope, the range it last locked is unlocked automatically.
 ///
 /// For an example, please see the documentation of the [`Lock`](struct.Lock.html) structure.
-pub fn unlock(fd: RawFd) -> Result<(), errno::Errno> {
-  unsafe {
-    let errno = c_unlock(fd);
-
-    return match errno {
-       0 => Ok(()),
-       _ => Err(errno::Errno(errno)),
-    }
-  }
+pub fn unlock_range(fd: NativeHandle, offset: u64, len: u64) -> Result<(), Error> {
+    sys::unlock_range(fd, offset, len)
 }
-
-
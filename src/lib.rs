@@ -0,0 +1,23 @@
+//! A tiny library for obtaining advisory file locks.
+//!
+//! See [`fd::Lock`](fd/struct.Lock.html) for the primary API.
+
+extern crate errno;
+extern crate libc;
+
+#[cfg(windows)]
+extern crate winapi;
+
+#[cfg(test)]
+extern crate tempfile;
+
+pub mod fd;
+mod functions;
+pub mod rwlock;
+mod sys;
+mod util;
+
+pub use fd::Lock;
+pub use functions::Error;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use util::{Kind, Mode, ParseError};
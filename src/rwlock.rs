@@ -0,0 +1,245 @@
+//! An owning, RAII read/write file lock.
+//!
+//! `RwLock<T>` wraps a value that owns a native file handle (anything
+//! implementing `AsRawFd` on Unix, `AsRawHandle` on Windows) and hands out
+//! `RwLockReadGuard`/`RwLockWriteGuard`s that `Deref`/`DerefMut` to it and
+//! release the underlying file lock on `Drop` — the same shape as
+//! `std::sync::RwLock`, but backed by an OS file lock instead of an
+//! in-process mutex.
+
+use std::ops::{Deref, DerefMut};
+
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd as AsNativeHandle, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawHandle as AsNativeHandle, RawHandle};
+
+use fd::Lock;
+pub use functions::Error;
+use util::{Kind, Mode};
+
+#[cfg(unix)]
+fn native_handle<T: AsNativeHandle>(inner: &T) -> RawFd {
+    inner.as_raw_fd()
+}
+#[cfg(windows)]
+fn native_handle<T: AsNativeHandle>(inner: &T) -> RawHandle {
+    inner.as_raw_handle()
+}
+
+/// An owning read/write lock over a value that owns a file.
+///
+/// No lock is held until `read`, `write`, `try_read` or `try_write` is
+/// called; each returns a guard that releases the lock when dropped.
+#[derive(Debug)]
+pub struct RwLock<T: AsNativeHandle> {
+    inner: T,
+}
+
+impl<T: AsNativeHandle> RwLock<T> {
+    /// Wraps `inner`, taking ownership of it.
+    pub fn new(inner: T) -> RwLock<T> {
+        RwLock {
+            inner: inner,
+        }
+    }
+
+    fn lock(&self) -> Lock {
+        Lock::new(native_handle(&self.inner))
+    }
+
+    /// Blocks until a shared (read) lock can be obtained.
+    pub fn read(&self) -> Result<RwLockReadGuard<T>, Error> {
+        let lock = self.lock();
+
+        match lock.lock(Kind::Blocking, Mode::Read) {
+            Ok(())  => Ok(RwLockReadGuard { rwlock: self, lock: lock }),
+            Err(e)  => Err(e),
+        }
+    }
+
+    /// Blocks until an exclusive (write) lock can be obtained.
+    pub fn write(&mut self) -> Result<RwLockWriteGuard<T>, Error> {
+        let lock = self.lock();
+
+        match lock.lock(Kind::Blocking, Mode::Write) {
+            Ok(())  => Ok(RwLockWriteGuard { rwlock: self, lock: lock }),
+            Err(e)  => Err(e),
+        }
+    }
+
+    /// Attempts to obtain a shared (read) lock without blocking, returning
+    /// `Err(Error::WouldBlock)` if the file is already held exclusively.
+    pub fn try_read(&self) -> Result<RwLockReadGuard<T>, Error> {
+        let lock = self.lock();
+
+        match lock.lock(Kind::NonBlocking, Mode::Read) {
+            Ok(())  => Ok(RwLockReadGuard { rwlock: self, lock: lock }),
+            Err(e)  => Err(e),
+        }
+    }
+
+    /// Attempts to obtain an exclusive (write) lock without blocking,
+    /// returning `Err(Error::WouldBlock)` if the file is already locked.
+    ///
+    /// The `&mut self` borrow only lasts for the duration of the attempt: on
+    /// `WouldBlock` it is dropped along with the returned `Err`, so callers
+    /// can fall back to a blocking `write()` in the same match:
+    ///
+    /// ```
+    /// extern crate file_lock;
+    /// extern crate tempfile;
+    ///
+    /// use file_lock::{RwLock, Error};
+    ///
+    /// fn main() {
+    ///     let mut rw = RwLock::new(tempfile::tempfile().unwrap());
+    ///
+    ///     let _guard = match rw.try_write() {
+    ///         Ok(guard)             => guard,
+    ///         Err(Error::WouldBlock) => rw.write().unwrap(),
+    ///         Err(e)                => panic!("lock failed: {}", e),
+    ///     };
+    /// }
+    /// ```
+    pub fn try_write(&mut self) -> Result<RwLockWriteGuard<T>, Error> {
+        let lock = self.lock();
+
+        match lock.lock(Kind::NonBlocking, Mode::Write) {
+            Ok(())  => Ok(RwLockWriteGuard { rwlock: self, lock: lock }),
+            Err(e)  => Err(e),
+        }
+    }
+}
+
+/// A guard granting shared read access to the value owned by an `RwLock`.
+/// The underlying file lock is released when the guard is dropped.
+#[derive(Debug)]
+pub struct RwLockReadGuard<'a, T: AsNativeHandle + 'a> {
+    rwlock: &'a RwLock<T>,
+    #[allow(dead_code)]
+    lock: Lock,
+}
+
+impl<'a, T: AsNativeHandle + 'a> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.rwlock.inner
+    }
+}
+
+/// A guard granting exclusive write access to the value owned by an
+/// `RwLock`. The underlying file lock is released when the guard is
+/// dropped.
+#[derive(Debug)]
+pub struct RwLockWriteGuard<'a, T: AsNativeHandle + 'a> {
+    rwlock: &'a mut RwLock<T>,
+    #[allow(dead_code)]
+    lock: Lock,
+}
+
+impl<'a, T: AsNativeHandle + 'a> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.rwlock.inner
+    }
+}
+
+impl<'a, T: AsNativeHandle + 'a> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.rwlock.inner
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::ptr;
+    use libc;
+
+    use fd::Lock;
+
+    /// Mirrors `fd::tests::run_in_child`: `fcntl` locks are scoped to a
+    /// process, so exercising contention against an `RwLock` genuinely
+    /// requires a second process.
+    fn run_in_child<F: FnOnce() -> Result<(), Error>>(child: F) -> u8 {
+        let (mut parent_sock, mut child_sock) = UnixStream::pair().unwrap();
+
+        match unsafe { libc::fork() } {
+            0 => {
+                let code = match child() {
+                    Ok(())                 => 0u8,
+                    Err(Error::WouldBlock) => 1u8,
+                    Err(_)                 => 2u8,
+                };
+                child_sock.write_all(&[code]).unwrap();
+                unsafe { libc::_exit(0) };
+            },
+            pid if pid > 0 => {
+                let mut code = [0u8; 1];
+                parent_sock.read_exact(&mut code).unwrap();
+                unsafe { libc::waitpid(pid, ptr::null_mut(), 0) };
+                code[0]
+            },
+            _ => panic!("fork() failed"),
+        }
+    }
+
+    #[test]
+    fn try_write_fails_while_other_process_holds_write_lock() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock(Kind::Blocking, Mode::Write).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            let mut rw = RwLock::new(other);
+            rw.try_write().map(|_| ())
+        });
+
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    fn try_read_succeeds_while_other_process_holds_read_lock() {
+        let f = ::tempfile::tempfile().unwrap();
+        let held = Lock::new(f.as_raw_fd());
+        held.lock(Kind::Blocking, Mode::Read).unwrap();
+
+        let other = f.try_clone().unwrap();
+        let result = run_in_child(move || {
+            let rw = RwLock::new(other);
+            rw.try_read().map(|_| ())
+        });
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn guard_derefs_read_and_write_the_wrapped_file() {
+        let mut f = ::tempfile::tempfile().unwrap();
+        f.write_all(b"hello").unwrap();
+
+        let mut rw = RwLock::new(f);
+
+        {
+            let mut guard = rw.write().unwrap();
+            guard.seek(SeekFrom::Start(0)).unwrap();
+            guard.write_all(b"bye!!").unwrap();
+        }
+
+        let mut buf = [0u8; 5];
+        {
+            let mut guard = rw.write().unwrap();
+            guard.seek(SeekFrom::Start(0)).unwrap();
+            guard.read_exact(&mut buf).unwrap();
+        }
+
+        assert_eq!(&buf, b"bye!!");
+    }
+}
@@ -0,0 +1,16 @@
+//! Platform-specific lock backends.
+//!
+//! `functions::lock_range`/`functions::unlock_range` dispatch to whichever
+//! of these is active for the target platform. Both backends expose the
+//! same signatures (modulo the native handle type), so the public
+//! `Kind`/`Mode` API stays portable.
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use self::unix::{lock_range, unlock_range};
+#[cfg(windows)]
+pub use self::windows::{lock_range, unlock_range};
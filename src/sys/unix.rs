@@ -0,0 +1,56 @@
+//! Unix lock backend, implemented directly against `fcntl`/`libc` — no C
+//! shim or build-time compilation step required.
+
+use std::os::unix::io::RawFd;
+use std::mem;
+use errno;
+use libc;
+
+use functions::Error;
+use util::{Kind, Mode};
+
+fn fcntl_lock(fd: RawFd, cmd: libc::c_int, l_type: libc::c_short, offset: u64, len: u64) -> Result<(), Error> {
+    let mut flock: libc::flock = unsafe { mem::zeroed() };
+
+    flock.l_type   = l_type;
+    flock.l_whence = libc::SEEK_SET as libc::c_short;
+    flock.l_start  = offset as libc::off_t;
+    flock.l_len    = len as libc::off_t;
+
+    let ret = unsafe { libc::fcntl(fd, cmd, &mut flock) };
+
+    if ret == -1 {
+        let errno = errno::errno();
+
+        return match errno.0 {
+            libc::EACCES | libc::EAGAIN => Err(Error::WouldBlock),
+            _                           => Err(Error::Errno(errno)),
+        };
+    }
+
+    Ok(())
+}
+
+/// Locks the byte range `[offset, offset + len)` of `fd`, or the range from
+/// `offset` to the end of the file when `len == 0`. The whole-file lock is
+/// `lock_range(fd, kind, mode, 0, 0)`.
+pub fn lock_range(fd: RawFd, kind: Kind, mode: Mode, offset: u64, len: u64) -> Result<(), Error> {
+    let l_type = match mode {
+        Mode::Read  => libc::F_RDLCK,
+        Mode::Write => libc::F_WRLCK,
+    } as libc::c_short;
+
+    let cmd = match kind {
+        Kind::NonBlocking => libc::F_SETLK,
+        Kind::Blocking    => libc::F_SETLKW,
+    };
+
+    fcntl_lock(fd, cmd, l_type, offset, len)
+}
+
+/// Unlocks exactly the byte range `[offset, offset + len)` of `fd` (the
+/// range a prior `lock_range` call locked), or the range from `offset` to
+/// the end of the file when `len == 0`.
+pub fn unlock_range(fd: RawFd, offset: u64, len: u64) -> Result<(), Error> {
+    fcntl_lock(fd, libc::F_SETLK, libc::F_UNLCK as libc::c_short, offset, len)
+}
@@ -0,0 +1,78 @@
+//! Windows lock backend, implemented via `LockFileEx`/`UnlockFile`.
+
+use std::io;
+use std::mem;
+use std::os::windows::io::RawHandle;
+
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::shared::winerror::{ERROR_IO_PENDING, ERROR_LOCK_VIOLATION};
+use winapi::um::fileapi::{LockFileEx, UnlockFile};
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::winbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY};
+
+use functions::Error;
+use util::{Kind, Mode};
+
+/// Splits a `len == 0` ("to EOF") byte count into the `(low, high)` DWORD
+/// pair `LockFileEx`/`UnlockFile` expect, using the conventional
+/// `0xffffffff, 0xffffffff` sentinel for "to the end of the file".
+fn split_len(len: u64) -> (u32, u32) {
+    if len == 0 {
+        (!0u32, !0u32)
+    } else {
+        ((len & 0xffff_ffff) as u32, (len >> 32) as u32)
+    }
+}
+
+/// Locks the byte range `[offset, offset + len)` of `handle`, or the range
+/// from `offset` to the end of the file when `len == 0`. The whole-file
+/// lock is `lock_range(handle, kind, mode, 0, 0)`.
+pub fn lock_range(handle: RawHandle, kind: Kind, mode: Mode, offset: u64, len: u64) -> Result<(), Error> {
+    let mut flags: DWORD = match mode {
+        Mode::Write => LOCKFILE_EXCLUSIVE_LOCK,
+        Mode::Read  => 0,
+    };
+
+    if let Kind::NonBlocking = kind {
+        flags |= LOCKFILE_FAIL_IMMEDIATELY;
+    }
+
+    let (len_low, len_high) = split_len(len);
+
+    let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+    unsafe {
+        overlapped.u.s_mut().Offset     = (offset & 0xffff_ffff) as u32;
+        overlapped.u.s_mut().OffsetHigh = (offset >> 32) as u32;
+    }
+
+    let ok = unsafe { LockFileEx(handle as *mut _, flags, 0, len_low, len_high, &mut overlapped) };
+
+    if ok == FALSE {
+        return Err(match io::Error::last_os_error().raw_os_error() {
+            Some(code) if code == ERROR_LOCK_VIOLATION as i32
+                       || code == ERROR_IO_PENDING as i32
+                => Error::WouldBlock,
+            Some(code) => Error::Os(code),
+            None => Error::Os(0),
+        });
+    }
+
+    Ok(())
+}
+
+/// Unlocks exactly the byte range `[offset, offset + len)` of `handle` (the
+/// range a prior `lock_range` call locked), or the range from `offset` to
+/// the end of the file when `len == 0`.
+pub fn unlock_range(handle: RawHandle, offset: u64, len: u64) -> Result<(), Error> {
+    let (len_low, len_high) = split_len(len);
+
+    let ok = unsafe {
+        UnlockFile(handle as *mut _, (offset & 0xffff_ffff) as u32, (offset >> 32) as u32, len_low, len_high)
+    };
+
+    if ok == FALSE {
+        return Err(Error::Os(io::Error::last_os_error().raw_os_error().unwrap_or(0)));
+    }
+
+    Ok(())
+}
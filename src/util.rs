@@ -0,0 +1,72 @@
+//! Contains the small set of types shared between the public API and the
+//! platform backends in `sys`.
+
+use std::error::Error as ErrorTrait;
+use std::fmt;
+use std::str::FromStr;
+
+/// Represents the way in which a lock is acquired.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Kind {
+    /// Return immediately with `Error::WouldBlock` if the lock cannot be
+    /// obtained right away.
+    NonBlocking,
+    /// Block the calling thread until the lock can be obtained.
+    Blocking,
+}
+
+impl FromStr for Kind {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Kind, ParseError> {
+        match s {
+            "non-blocking" => Ok(Kind::NonBlocking),
+            "blocking"     => Ok(Kind::Blocking),
+            _              => Err(ParseError),
+        }
+    }
+}
+
+/// Represents the kind of lock to acquire.
+///
+/// A `Read` lock is shared: any number of processes may hold it on the same
+/// file at once. A `Write` lock is exclusive: obtaining one requires that no
+/// other process holds either a `Read` or a `Write` lock on the file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Mode {
+    /// A shared lock. Multiple processes may hold a `Read` lock on the same
+    /// file simultaneously.
+    Read,
+    /// An exclusive lock. Only one process may hold a `Write` lock, and only
+    /// if no other process holds a `Read` or `Write` lock.
+    Write,
+}
+
+impl FromStr for Mode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Mode, ParseError> {
+        match s {
+            "read"  => Ok(Mode::Read),
+            "write" => Ok(Mode::Write),
+            _       => Err(ParseError),
+        }
+    }
+}
+
+/// Returned by the `FromStr` implementations of `Kind` and `Mode` when the
+/// given string doesn't name a known variant.
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError;
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        "unrecognized lock kind/mode".fmt(f)
+    }
+}
+
+impl ErrorTrait for ParseError {
+    fn description(&self) -> &str {
+        "unrecognized lock kind/mode"
+    }
+}